@@ -0,0 +1,452 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A minimal client for the standard `Content-Length`-framed JSON-RPC
+//! protocol spoken by language servers. One `LspClient` is launched for
+//! each tab whose language has a registered server and lives until that
+//! tab is deleted; diagnostics and
+//! semantic tokens it receives are translated into the editor's existing
+//! line/span representation so they can be delivered through the same
+//! sink as plugin-provided syntax highlighting.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// How long `initialize` will wait for a response before giving up on a
+/// server that isn't dead, just not answering. A server that's merely
+/// slow to start still gets a generous window; one that's truly wedged
+/// can no longer freeze every tab's RPC handling forever.
+const INITIALIZE_TIMEOUT: Duration = Duration::from_secs(10);
+
+use serde_json::{self, Value};
+use serde_json::builder::ObjectBuilder;
+
+/// A line's worth of color/diagnostic spans, translated from a server
+/// response into the shape `Editor::plugin_set_line_fg_spans` expects.
+pub struct LineSpans {
+    pub line_num: usize,
+    pub spans: Value,
+}
+
+/// A running language server connection for a single language.
+pub struct LspClient {
+    language: String,
+    stdin: ChildStdin,
+    child: Child,
+    next_id: u64,
+    incoming: Receiver<Value>,
+}
+
+impl LspClient {
+    /// Spawns `exec_path` and performs the `initialize` handshake. The
+    /// server's stdout is read on a background thread, so a slow or silent
+    /// server blocks the caller for at most `INITIALIZE_TIMEOUT`.
+    pub fn launch(language: &str, exec_path: &str) -> io::Result<LspClient> {
+        let mut child = Command::new(exec_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("child stdin was piped");
+        let stdout = child.stdout.take().expect("child stdout was piped");
+
+        let (tx, rx) = channel();
+        thread::spawn(move || read_messages(stdout, tx));
+
+        let mut client = LspClient {
+            language: language.to_string(),
+            stdin: stdin,
+            child: child,
+            next_id: 0,
+            incoming: rx,
+        };
+        client.initialize()?;
+        Ok(client)
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Sends `initialize`, blocks for its response, then sends `initialized`
+    /// -- the server is not allowed to receive `didOpen`/`didChange` before
+    /// that notification goes out, so `launch` must not return until here.
+    fn initialize(&mut self) -> io::Result<()> {
+        let id = self.send_request("initialize",
+            &ObjectBuilder::new().insert("processId", Value::Null).unwrap())?;
+        self.await_response(id)?;
+        self.send_notification("initialized", &ObjectBuilder::new().unwrap())
+    }
+
+    /// Blocks until the response to request `id` comes back, discarding
+    /// anything else that arrives first, or until `INITIALIZE_TIMEOUT`
+    /// elapses. Only used during the handshake, where nothing else is
+    /// expected before the response -- a server that never answers must
+    /// not be allowed to block `launch` (and whatever dispatch thread
+    /// called it) forever.
+    fn await_response(&mut self, id: u64) -> io::Result<()> {
+        let deadline = ::std::time::Instant::now() + INITIALIZE_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(::std::time::Instant::now());
+            match self.incoming.recv_timeout(remaining) {
+                Ok(message) => {
+                    if message.find("id").and_then(Value::as_u64) == Some(id) {
+                        return Ok(());
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::Other,
+                    "lsp server closed the connection before responding to initialize")),
+                Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(io::ErrorKind::TimedOut,
+                    "lsp server did not respond to initialize in time")),
+            }
+        }
+    }
+
+    /// `textDocument/didOpen` for a freshly opened buffer.
+    pub fn did_open(&mut self, uri: &str, text: &str) -> io::Result<()> {
+        self.send_notification("textDocument/didOpen",
+            &ObjectBuilder::new()
+                .insert_object("textDocument", |b| {
+                    b.insert("uri", uri).insert("text", text)
+                })
+                .unwrap())
+    }
+
+    /// `textDocument/didChange` with a single incremental range, derived
+    /// by the caller from the edit's `Rope` delta.
+    pub fn did_change(&mut self, uri: &str, start_line: usize, start_col: usize,
+            end_line: usize, end_col: usize, replacement: &str) -> io::Result<()> {
+        self.send_notification("textDocument/didChange",
+            &ObjectBuilder::new()
+                .insert_object("textDocument", |b| b.insert("uri", uri))
+                .insert_array("contentChanges", |b| {
+                    b.push(ObjectBuilder::new()
+                        .insert_object("range", |b| {
+                            b.insert_object("start", |b| {
+                                b.insert("line", start_line).insert("character", start_col)
+                            }).insert_object("end", |b| {
+                                b.insert("line", end_line).insert("character", end_col)
+                            })
+                        })
+                        .insert("text", replacement)
+                        .unwrap())
+                })
+                .unwrap())
+    }
+
+    /// Drains whatever `publishDiagnostics` or semantic-token notifications
+    /// have arrived since the last poll, already translated into spans.
+    /// Never blocks: an empty `Vec` just means nothing new has arrived.
+    pub fn poll_spans(&mut self) -> Vec<LineSpans> {
+        let mut out = Vec::new();
+        loop {
+            match self.incoming.try_recv() {
+                Ok(msg) => out.extend(translate_to_spans(&msg)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        out
+    }
+
+    /// Shuts the server down. Errors are not fatal to the caller: a
+    /// server that's already gone just means there's nothing to do.
+    pub fn shutdown(mut self) {
+        let _ = self.send_request("shutdown", &Value::Null);
+        let _ = self.send_notification("exit", &Value::Null);
+        let _ = self.child.kill();
+    }
+
+    /// Sends a request and returns the id it was assigned, so the caller
+    /// can correlate the eventual response.
+    fn send_request(&mut self, method: &str, params: &Value) -> io::Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.write_message(&ObjectBuilder::new()
+            .insert("jsonrpc", "2.0")
+            .insert("id", id)
+            .insert("method", method)
+            .insert("params", params)
+            .unwrap())?;
+        Ok(id)
+    }
+
+    fn send_notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        self.write_message(&ObjectBuilder::new()
+            .insert("jsonrpc", "2.0")
+            .insert("method", method)
+            .insert("params", params)
+            .unwrap())
+    }
+
+    fn write_message(&mut self, message: &Value) -> io::Result<()> {
+        let body = serde_json::to_string(message)?;
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.stdin.flush()
+    }
+}
+
+/// Reads `Content-Length`-framed messages from `stdout` until the pipe
+/// closes, forwarding each parsed body over `tx`. Runs on its own thread
+/// so a server that goes quiet doesn't block the editor.
+fn read_messages<R: Read>(stdout: R, tx: ::std::sync::mpsc::Sender<Value>) {
+    let mut reader = BufReader::new(stdout);
+    loop {
+        let mut content_length = None;
+        loop {
+            let mut header = String::new();
+            match reader.read_line(&mut header) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if header.starts_with("Content-Length:") {
+                content_length = header["Content-Length:".len()..].trim().parse().ok();
+            }
+        }
+
+        let len = match content_length {
+            Some(len) => len,
+            None => return,
+        };
+        let mut body = vec![0u8; len];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        if let Ok(value) = serde_json::from_slice(&body) {
+            if tx.send(value).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Translates a single `publishDiagnostics` or semantic-token
+/// notification into the line/span pairs the editor already knows how to
+/// render, so no new front-end protocol is needed.
+fn translate_to_spans(message: &Value) -> Vec<LineSpans> {
+    let method = message.find("method").and_then(Value::as_string);
+    match method {
+        Some("textDocument/publishDiagnostics") => diagnostics_to_spans(message),
+        Some("textDocument/semanticTokens/full") => semantic_tokens_to_spans(message),
+        _ => Vec::new(),
+    }
+}
+
+fn diagnostics_to_spans(message: &Value) -> Vec<LineSpans> {
+    let diagnostics = message.find("params")
+        .and_then(|params| params.find("diagnostics"))
+        .and_then(Value::as_array);
+    let diagnostics = match diagnostics {
+        Some(d) => d,
+        None => return Vec::new(),
+    };
+
+    diagnostics.iter().flat_map(|d| {
+        diagnostic_to_spans(d).unwrap_or_default()
+    }).collect()
+}
+
+/// Turns a single diagnostic's range into one `LineSpans` per line it
+/// covers. A range that starts and ends on the same line produces exactly
+/// one; a multi-line range (routine for type/borrow errors) produces one
+/// per covered line, each clamped to that line's portion of the range
+/// rather than smearing the end column across every line.
+fn diagnostic_to_spans(d: &Value) -> Option<Vec<LineSpans>> {
+    let range = d.find("range")?;
+    let start = range.find("start")?;
+    let end = range.find("end")?;
+    let start_line = start.find("line").and_then(Value::as_u64)?;
+    let end_line = end.find("line").and_then(Value::as_u64)?;
+    let start_col = start.find("character").and_then(Value::as_u64)?;
+    let end_col = end.find("character").and_then(Value::as_u64)?;
+
+    if start_line > end_line {
+        return None;
+    }
+
+    Some((start_line..=end_line).map(|line| {
+        let (line_start, line_end) = if start_line == end_line {
+            (start_col, end_col)
+        } else if line == start_line {
+            (start_col, ::std::u64::MAX)
+        } else if line == end_line {
+            (0, end_col)
+        } else {
+            (0, ::std::u64::MAX)
+        };
+        LineSpans {
+            line_num: line as usize,
+            spans: Value::Array(vec![
+                ObjectBuilder::new()
+                    .insert("start", line_start)
+                    .insert("end", line_end)
+                    .insert("style", "diagnostic")
+                    .unwrap(),
+            ]),
+        }
+    }).collect())
+}
+
+/// The semantic-tokens payload is a flat array of
+/// `(deltaLine, deltaStartChar, length, tokenType, tokenModifiers)`
+/// quintuples, each relative to the previous token (`deltaStartChar` is
+/// relative to the previous token's start column if `deltaLine` is 0, or
+/// to column 0 of the new line otherwise). This walks that delta encoding to recover absolute
+/// line/column spans, grouping every span on the same line into one
+/// `LineSpans`.
+fn semantic_tokens_to_spans(message: &Value) -> Vec<LineSpans> {
+    let data = message.find("params").and_then(|p| p.find("data"))
+        .or_else(|| message.find("result").and_then(|r| r.find("data")))
+        .and_then(Value::as_array);
+    let data = match data {
+        Some(d) => d,
+        None => return Vec::new(),
+    };
+
+    let mut line = 0u64;
+    let mut start_col = 0u64;
+    let mut out: Vec<LineSpans> = Vec::new();
+
+    for token in data.chunks(5) {
+        if token.len() < 5 {
+            break;
+        }
+        let delta_line = match token[0].as_u64() { Some(n) => n, None => break };
+        let delta_start = match token[1].as_u64() { Some(n) => n, None => break };
+        let length = match token[2].as_u64() { Some(n) => n, None => break };
+        let token_type = match token[3].as_u64() { Some(n) => n, None => break };
+
+        if delta_line > 0 {
+            line += delta_line;
+            start_col = delta_start;
+        } else {
+            start_col += delta_start;
+        }
+
+        let span = ObjectBuilder::new()
+            .insert("start", start_col)
+            .insert("end", start_col + length)
+            .insert("style", format!("semantic.{}", token_type))
+            .unwrap();
+
+        match out.iter_mut().find(|ls| ls.line_num == line as usize) {
+            Some(existing) => {
+                if let Value::Array(ref mut spans) = existing.spans {
+                    spans.push(span);
+                }
+            }
+            None => out.push(LineSpans { line_num: line as usize, spans: Value::Array(vec![span]) }),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(start_line: u64, start_col: u64, end_line: u64, end_col: u64) -> Value {
+        ObjectBuilder::new()
+            .insert("method", "textDocument/publishDiagnostics")
+            .insert_object("params", |b| {
+                b.insert_array("diagnostics", |b| {
+                    b.push(ObjectBuilder::new()
+                        .insert_object("range", |b| {
+                            b.insert_object("start", |b| {
+                                b.insert("line", start_line).insert("character", start_col)
+                            }).insert_object("end", |b| {
+                                b.insert("line", end_line).insert("character", end_col)
+                            })
+                        })
+                        .unwrap())
+                })
+            })
+            .unwrap()
+    }
+
+    fn span_range(spans: &Value) -> (u64, u64) {
+        match *spans {
+            Value::Array(ref spans) => {
+                let span = &spans[0];
+                (span.find("start").and_then(Value::as_u64).unwrap(),
+                 span.find("end").and_then(Value::as_u64).unwrap())
+            }
+            _ => panic!("expected an array of spans"),
+        }
+    }
+
+    #[test]
+    fn single_line_diagnostic_produces_one_span() {
+        let spans = diagnostics_to_spans(&diagnostic(4, 2, 4, 9));
+
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].line_num, 4);
+        assert_eq!(span_range(&spans[0].spans), (2, 9));
+    }
+
+    #[test]
+    fn multi_line_diagnostic_produces_a_span_per_covered_line() {
+        let spans = diagnostics_to_spans(&diagnostic(4, 8, 6, 3));
+
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].line_num, 4);
+        assert_eq!(spans[1].line_num, 5);
+        assert_eq!(spans[2].line_num, 6);
+
+        // The first line's span starts where the range starts and runs to
+        // the end of that line, not to `end.character` (which belongs to a
+        // different line entirely).
+        assert_eq!(span_range(&spans[0].spans).0, 8);
+        // The last line's span ends where the range ends.
+        assert_eq!(span_range(&spans[2].spans).1, 3);
+        // The middle line is covered in full.
+        assert_eq!(span_range(&spans[1].spans).0, 0);
+    }
+
+    #[test]
+    fn translate_to_spans_ignores_unknown_methods() {
+        let message = ObjectBuilder::new().insert("method", "textDocument/hover").unwrap();
+        assert!(translate_to_spans(&message).is_empty());
+    }
+
+    #[test]
+    fn semantic_tokens_decodes_relative_deltas_into_absolute_spans() {
+        let message = ObjectBuilder::new()
+            .insert("method", "textDocument/semanticTokens/full")
+            .insert_object("params", |b| {
+                b.insert_array("data", |b| {
+                    b.push(0).push(2).push(3).push(1).push(0)
+                        .push(1).push(4).push(5).push(2).push(0)
+                })
+            })
+            .unwrap();
+
+        let spans = semantic_tokens_to_spans(&message);
+
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].line_num, 0);
+        assert_eq!(span_range(&spans[0].spans), (2, 5));
+        assert_eq!(spans[1].line_num, 1);
+        assert_eq!(span_range(&spans[1].spans), (4, 9));
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Discovers installable plugins by scanning a directory of manifests, and
+//! decides which of them should be started for a given buffer.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use plugin_manifest::Manifest;
+
+/// The manifest name, used to suffix plugin directories: `<plugins_dir>/<name>/manifest.toml`.
+const MANIFEST_FILE_NAME: &'static str = "manifest.toml";
+
+/// All plugins known to the editor, as discovered from a plugins directory
+/// at startup.
+pub struct PluginCatalog {
+    manifests: Vec<Manifest>,
+}
+
+impl PluginCatalog {
+    /// An empty catalog, for embedders that don't want plugin autostart.
+    pub fn empty() -> PluginCatalog {
+        PluginCatalog { manifests: Vec::new() }
+    }
+
+    /// Scans `plugins_dir` for one manifest per subdirectory. A manifest
+    /// that fails to parse is logged and skipped, rather than aborting the
+    /// whole scan.
+    pub fn scan(plugins_dir: &Path) -> PluginCatalog {
+        let mut manifests = Vec::new();
+
+        let entries = match fs::read_dir(plugins_dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                print_err!("couldn't read plugins dir {}: {}", plugins_dir.display(), e);
+                return PluginCatalog { manifests: manifests };
+            }
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let manifest_path = entry.path().join(MANIFEST_FILE_NAME);
+            if !manifest_path.exists() {
+                continue;
+            }
+            match Manifest::load(&manifest_path) {
+                Ok(manifest) => manifests.push(manifest),
+                Err(e) => print_err!("skipping plugin manifest: {}", e),
+            }
+        }
+
+        PluginCatalog { manifests: manifests }
+    }
+
+    /// Every manifest whose activation rules match a buffer with the given
+    /// file path (if any) and line count.
+    pub fn matching(&self, file_path: Option<&Path>, n_lines: usize) -> Vec<&Manifest> {
+        self.manifests.iter().filter(|m| m.matches(file_path, n_lines)).collect()
+    }
+
+    pub fn exec_paths(&self) -> Vec<PathBuf> {
+        self.manifests.iter().map(|m| m.exec_path.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    fn plugins_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("xi-plugin-catalog-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_manifest(plugins_dir: &Path, plugin_name: &str, contents: &[u8]) {
+        let dir = plugins_dir.join(plugin_name);
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join(MANIFEST_FILE_NAME)).unwrap().write_all(contents).unwrap();
+    }
+
+    #[test]
+    fn scan_of_missing_directory_returns_an_empty_catalog() {
+        let dir = ::std::env::temp_dir().join("xi-plugin-catalog-test-missing");
+        let _ = fs::remove_dir_all(&dir);
+
+        let catalog = PluginCatalog::scan(&dir);
+
+        assert!(catalog.exec_paths().is_empty());
+    }
+
+    #[test]
+    fn scan_skips_a_malformed_manifest_instead_of_panicking() {
+        let dir = plugins_dir("malformed");
+        write_manifest(&dir, "good-plugin", br#"
+            name = "good-plugin"
+            version = "0.1.0"
+            exec_path = "/bin/good-plugin"
+            activations = ["Always"]
+        "#);
+        write_manifest(&dir, "bad-plugin", b"this is not valid toml { } [[[");
+
+        let catalog = PluginCatalog::scan(&dir);
+
+        assert_eq!(catalog.exec_paths(), vec![PathBuf::from("/bin/good-plugin")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn scan_skips_plugin_directories_with_no_manifest() {
+        let dir = plugins_dir("no-manifest");
+        fs::create_dir_all(dir.join("not-a-plugin")).unwrap();
+
+        let catalog = PluginCatalog::scan(&dir);
+
+        assert!(catalog.exec_paths().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of per-plugin manifest files, which describe where a plugin's
+//! executable lives and when it should be started.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use toml;
+
+/// A condition under which a plugin should be automatically started for
+/// a buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum Activation {
+    /// Start for any buffer whose file name ends with this extension
+    /// (without the leading dot, e.g. `"rs"`).
+    FileExtension(String),
+    /// Always start, regardless of the buffer being opened.
+    Always,
+    /// Start once the buffer has at least this many lines.
+    MinBufferLines(usize),
+}
+
+/// A capability a plugin has been granted. `PluginCtx` consults this list
+/// before honoring an `alert` or `set_line_fg_spans` request from any
+/// plugin, whatever its activations.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum Capability {
+    Alert,
+    SetLineFgSpans,
+}
+
+/// The parsed contents of a single plugin's manifest file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    pub exec_path: PathBuf,
+    pub activations: Vec<Activation>,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+}
+
+impl Manifest {
+    /// Loads and parses a manifest from `path`. Returns an error describing
+    /// what went wrong rather than panicking, so a single bad manifest
+    /// doesn't take down catalog loading.
+    pub fn load(path: &Path) -> Result<Manifest, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .map_err(|e| format!("couldn't open {}: {}", path.display(), e))?
+            .read_to_string(&mut contents)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+
+        toml::from_str(&contents)
+            .map_err(|e| format!("invalid manifest in {}: {}", path.display(), e))
+    }
+
+    /// Whether this manifest's activation rules match a buffer with the
+    /// given file path (if any) and line count.
+    pub fn matches(&self, file_path: Option<&Path>, n_lines: usize) -> bool {
+        self.activations.iter().any(|activation| match *activation {
+            Activation::Always => true,
+            Activation::MinBufferLines(min) => n_lines >= min,
+            Activation::FileExtension(ref ext) => {
+                file_path
+                    .and_then(|p| p.extension())
+                    .and_then(|e| e.to_str())
+                    .map_or(false, |actual| actual == ext)
+            }
+        })
+    }
+
+    pub fn allows(&self, capability: &Capability) -> bool {
+        self.capabilities.contains(capability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_manifest(name: &str, contents: &[u8]) -> PathBuf {
+        let path = ::std::env::temp_dir().join(format!("xi-manifest-test-{}.toml", name));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_parses_a_well_formed_manifest() {
+        let path = write_manifest("well-formed", br#"
+            name = "rust-analyzer"
+            version = "0.1.0"
+            exec_path = "/usr/bin/rust-analyzer"
+            activations = [{ FileExtension = "rs" }]
+        "#);
+
+        let manifest = Manifest::load(&path).unwrap();
+
+        assert_eq!(manifest.name, "rust-analyzer");
+        assert_eq!(manifest.activations, vec![Activation::FileExtension("rs".to_string())]);
+        assert!(manifest.capabilities.is_empty());
+
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn load_reports_malformed_toml_instead_of_panicking() {
+        let path = write_manifest("malformed", b"this is not valid toml { } [[[");
+
+        let err = Manifest::load(&path).unwrap_err();
+
+        assert!(err.contains(&path.display().to_string()));
+
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn load_reports_missing_required_fields() {
+        let path = write_manifest("missing-fields", br#"
+            name = "incomplete"
+        "#);
+
+        assert!(Manifest::load(&path).is_err());
+
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn matches_checks_every_activation_rule() {
+        let manifest = Manifest {
+            name: "foo".to_string(),
+            version: "0.1.0".to_string(),
+            exec_path: PathBuf::from("/bin/foo"),
+            activations: vec![Activation::FileExtension("rs".to_string()), Activation::MinBufferLines(100)],
+            capabilities: Vec::new(),
+        };
+
+        assert!(manifest.matches(Some(Path::new("main.rs")), 1));
+        assert!(manifest.matches(Some(Path::new("main.py")), 200));
+        assert!(!manifest.matches(Some(Path::new("main.py")), 1));
+    }
+
+    #[test]
+    fn allows_checks_the_capability_list() {
+        let manifest = Manifest {
+            name: "foo".to_string(),
+            version: "0.1.0".to_string(),
+            exec_path: PathBuf::from("/bin/foo"),
+            activations: Vec::new(),
+            capabilities: vec![Capability::Alert],
+        };
+
+        assert!(manifest.allows(&Capability::Alert));
+        assert!(!manifest.allows(&Capability::SetLineFgSpans));
+    }
+}
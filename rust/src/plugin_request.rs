@@ -0,0 +1,183 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bookkeeping for in-flight plugin RPCs, so a slow or disconnected plugin
+//! can't leave the editor waiting forever on a response that will never
+//! come.
+
+use std::collections::BTreeMap;
+
+/// The state of a single outstanding plugin request, modeled as a small
+/// coroutine: a request starts `Blocked` on the editor computing an
+/// answer, becomes `Suspended` once the answer has been sent back and
+/// we're waiting on the plugin to resume, and ends `Finished` either
+/// because the plugin acknowledged it or because it can no longer be
+/// delivered (the tab closed, or the plugin disconnected).
+///
+/// `Running` is included for completeness with the coroutine model but is
+/// not currently produced by the editor side: a request is `Blocked` the
+/// instant it's recorded. `Finished` is never stored either: finishing a
+/// request removes it from the `RequestTable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginRequestState {
+    Running,
+    Blocked,
+    Suspended,
+    Finished,
+}
+
+#[derive(Debug, Clone)]
+pub struct PluginRequest {
+    pub tab: String,
+    pub plugin_name: Option<String>,
+    pub state: PluginRequestState,
+}
+
+/// All outstanding plugin requests across every tab and plugin, plus the
+/// counter used to hand out fresh ids. Ids are never reused within a
+/// session, so a stale response can always be recognized and dropped.
+#[derive(Default)]
+pub struct RequestTable {
+    next_id: u64,
+    requests: BTreeMap<u64, PluginRequest>,
+}
+
+impl RequestTable {
+    pub fn new() -> RequestTable {
+        RequestTable { next_id: 0, requests: BTreeMap::new() }
+    }
+
+    /// Allocates a fresh id and records it as `Blocked` for `tab`.
+    pub fn begin(&mut self, tab: &str, plugin_name: Option<&str>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.requests.insert(id, PluginRequest {
+            tab: tab.to_string(),
+            plugin_name: plugin_name.map(|s| s.to_string()),
+            state: PluginRequestState::Blocked,
+        });
+        id
+    }
+
+    /// Moves `id` from `Blocked` to `Suspended`, once the editor has
+    /// computed an answer and is about to hand it back to the plugin.
+    pub fn suspend(&mut self, id: u64) {
+        if let Some(req) = self.requests.get_mut(&id) {
+            req.state = PluginRequestState::Suspended;
+        }
+    }
+
+    /// Finishes `id` by pruning it right away rather than keeping it as
+    /// `Finished`, since nothing consults a finished request -- otherwise `requests`
+    /// would grow for the life of the process, as ids are never reused.
+    /// Idempotent, so it's safe to call from both the normal completion
+    /// path and cleanup paths (tab deletion, plugin disconnect) without
+    /// worrying about ordering.
+    pub fn finish(&mut self, id: u64) {
+        self.requests.remove(&id);
+    }
+
+    /// Whether `id` is still a live request whose response a plugin
+    /// should act on. A resume message for an id that isn't `Suspended`
+    /// here is stale and should be dropped.
+    pub fn is_suspended(&self, id: u64) -> bool {
+        self.requests.get(&id).map_or(false, |r| r.state == PluginRequestState::Suspended)
+    }
+
+    /// Finishes every in-flight request belonging to `tab`, e.g. because
+    /// the tab was just deleted and no response can be delivered to it.
+    pub fn finish_tab(&mut self, tab: &str) {
+        self.requests.retain(|_, req| req.tab != tab);
+    }
+
+    /// Finishes every in-flight request started by `plugin_name` in
+    /// `tab`, e.g. because that plugin just disconnected mid-request.
+    pub fn finish_plugin(&mut self, tab: &str, plugin_name: &str) {
+        self.requests.retain(|_, req| {
+            !(req.tab == tab && req.plugin_name.as_ref().map(String::as_str) == Some(plugin_name))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_never_reused() {
+        let mut table = RequestTable::new();
+        let first = table.begin("tab1", None);
+        table.finish(first);
+        let second = table.begin("tab1", None);
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn finish_tab_finishes_every_in_flight_request_for_that_tab() {
+        let mut table = RequestTable::new();
+        let a = table.begin("tab1", None);
+        let b = table.begin("tab1", Some("plugin-a"));
+        let other_tab = table.begin("tab2", None);
+
+        table.suspend(a);
+        table.suspend(b);
+        table.suspend(other_tab);
+
+        table.finish_tab("tab1");
+
+        assert!(!table.is_suspended(a));
+        assert!(!table.is_suspended(b));
+        assert!(table.is_suspended(other_tab));
+    }
+
+    #[test]
+    fn finish_plugin_only_finishes_requests_from_that_plugin_in_that_tab() {
+        let mut table = RequestTable::new();
+        let mine = table.begin("tab1", Some("plugin-a"));
+        let other_plugin = table.begin("tab1", Some("plugin-b"));
+        let other_tab = table.begin("tab2", Some("plugin-a"));
+
+        table.suspend(mine);
+        table.suspend(other_plugin);
+        table.suspend(other_tab);
+
+        table.finish_plugin("tab1", "plugin-a");
+
+        assert!(!table.is_suspended(mine));
+        assert!(table.is_suspended(other_plugin));
+        assert!(table.is_suspended(other_tab));
+    }
+
+    #[test]
+    fn finished_requests_are_pruned_instead_of_retained_forever() {
+        let mut table = RequestTable::new();
+        let id = table.begin("tab1", None);
+
+        table.finish(id);
+
+        assert_eq!(table.requests.len(), 0);
+    }
+
+    #[test]
+    fn finish_is_idempotent() {
+        let mut table = RequestTable::new();
+        let id = table.begin("tab1", None);
+
+        table.finish(id);
+        table.finish(id);
+
+        assert!(!table.is_suspended(id));
+    }
+}
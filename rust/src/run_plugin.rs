@@ -0,0 +1,175 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Launches a plugin process and carries the editor<->plugin RPC traffic
+//! over its stdin/stdout. Every notification is a single `serde_json::Value`,
+//! sent either as a newline-delimited JSON object (the default, and the
+//! only encoding a plugin is required to understand) or as a MessagePack
+//! value via `rmp-serde`, once a plugin has opted into the latter through
+//! the `ping_from_editor` handshake (see `PluginCtx::on_plugin_encoding` in
+//! `tabs.rs`). Both are read back the same way: peek the next byte, and
+//! let `{` decide whether the frame is a line of JSON or a self-delimiting
+//! MessagePack value.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use rmp_serde;
+use serde_json::{self, Value};
+
+fn to_io_err<E: ::std::fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+/// A handle to a running plugin process' input side. Cheap to clone: every
+/// clone writes to the same underlying pipe, so the one long-lived
+/// `PluginCtx` for a connection and anything that transiently borrows it
+/// can share a peer freely.
+#[derive(Clone)]
+pub struct PluginPeer {
+    stdin: Arc<Mutex<ChildStdin>>,
+    // Kept alive so the child isn't reaped (and its pipes torn down)
+    // while some clone of this peer is still in use.
+    child: Arc<Mutex<Child>>,
+}
+
+impl PluginPeer {
+    /// Sends `method`/`params` as a newline-delimited JSON object. Write
+    /// failures are logged, not propagated: a plugin that's gone deaf just
+    /// misses this notification.
+    pub fn send_rpc_notification(&self, method: &str, params: &Value) {
+        if let Err(e) = self.write_json(method, params) {
+            print_err!("failed to send {} to plugin: {}", method, e);
+        }
+    }
+
+    /// Same as `send_rpc_notification`, but framed as MessagePack. Only
+    /// sent to plugins that asked for it during the handshake.
+    pub fn send_rpc_notification_msgpack(&self, method: &str, params: &Value) {
+        if let Err(e) = self.write_msgpack(method, params) {
+            print_err!("failed to send {} (msgpack) to plugin: {}", method, e);
+        }
+    }
+
+    fn write_json(&self, method: &str, params: &Value) -> io::Result<()> {
+        let message = serde_json::builder::ObjectBuilder::new()
+            .insert("method", method)
+            .insert("params", params)
+            .unwrap();
+        let mut text = serde_json::to_string(&message).map_err(to_io_err)?;
+        text.push('\n');
+        self.stdin.lock().unwrap().write_all(text.as_bytes())
+    }
+
+    fn write_msgpack(&self, method: &str, params: &Value) -> io::Result<()> {
+        let message = serde_json::builder::ObjectBuilder::new()
+            .insert("method", method)
+            .insert("params", params)
+            .unwrap();
+        let bytes = rmp_serde::to_vec(&message).map_err(to_io_err)?;
+        self.stdin.lock().unwrap().write_all(&bytes)
+    }
+}
+
+/// Spawns `exec_path` and starts reading its stdout on a background
+/// thread, so a plugin that goes quiet can't block the caller. The
+/// returned `Receiver` yields one `Value` per inbound notification or
+/// request, regardless of which encoding the plugin used to send it.
+pub fn start_plugin(exec_path: &Path) -> io::Result<(PluginPeer, Receiver<Value>)> {
+    let mut child = Command::new(exec_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let stdin = child.stdin.take().expect("child stdin was piped");
+    let stdout = child.stdout.take().expect("child stdout was piped");
+
+    let (tx, rx) = channel();
+    thread::spawn(move || read_messages(stdout, tx));
+
+    let peer = PluginPeer {
+        stdin: Arc::new(Mutex::new(stdin)),
+        child: Arc::new(Mutex::new(child)),
+    };
+    Ok((peer, rx))
+}
+
+/// Reads one self-delimiting message at a time from `stdout` until the
+/// pipe closes: a `{` starts a line of JSON, anything else starts a
+/// MessagePack value. Runs on its own thread so a plugin that goes quiet
+/// doesn't block the editor.
+fn read_messages<R: Read>(stdout: R, tx: Sender<Value>) {
+    let mut reader = BufReader::new(stdout);
+    loop {
+        let first_byte = match reader.fill_buf() {
+            Ok(buf) if buf.is_empty() => return,
+            Ok(buf) => buf[0],
+            Err(_) => return,
+        };
+
+        let parsed = if first_byte == b'{' {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => serde_json::from_str(line.trim()).ok(),
+            }
+        } else {
+            rmp_serde::from_read(&mut reader).ok()
+        };
+
+        match parsed {
+            Some(value) => if tx.send(value).is_err() { return; },
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::builder::ObjectBuilder;
+
+    #[test]
+    fn msgpack_round_trips_plugin_payloads() {
+        let payload = ObjectBuilder::new()
+            .insert("method", "set_line_fg_spans_response")
+            .insert_object("params", |b| {
+                b.insert("id", 42).insert("result", Value::Null)
+            })
+            .unwrap();
+
+        let encoded = rmp_serde::to_vec(&payload).expect("encode");
+        let decoded: Value = rmp_serde::from_slice(&encoded).expect("decode");
+
+        assert_eq!(payload, decoded);
+    }
+
+    #[test]
+    fn json_and_msgpack_encode_the_same_value() {
+        let payload = ObjectBuilder::new()
+            .insert("method", "n_lines_response")
+            .insert_object("params", |b| b.insert("id", 7).insert("result", 120))
+            .unwrap();
+
+        let via_json: Value = serde_json::from_str(&serde_json::to_string(&payload).unwrap()).unwrap();
+        let via_msgpack: Value = rmp_serde::from_slice(&rmp_serde::to_vec(&payload).unwrap()).unwrap();
+
+        assert_eq!(via_json, via_msgpack);
+    }
+}
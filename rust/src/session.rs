@@ -0,0 +1,142 @@
+// Copyright 2016 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persists the set of open tabs to a TOML file in the OS config
+//! directory, so they can be reopened the next time the editor starts.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use toml;
+use xdg;
+
+const SESSION_FILE_NAME: &'static str = "session.toml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TabSession {
+    pub path: String,
+    pub selection_start: usize,
+    pub selection_end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionState {
+    pub id_counter: usize,
+    // Declared before `tabs`: TOML can't emit a plain value after an array
+    // of tables, so `tabs` has to be serialized last.
+    #[serde(default)]
+    pub kill_ring: Option<String>,
+    pub tabs: Vec<TabSession>,
+}
+
+impl SessionState {
+    pub fn empty() -> SessionState {
+        SessionState { id_counter: 0, tabs: Vec::new(), kill_ring: None }
+    }
+
+    /// The path this session would be read from and written to, if the
+    /// current environment has a usable config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        xdg::BaseDirectories::with_prefix("xi")
+            .ok()
+            .and_then(|dirs| dirs.place_config_file(SESSION_FILE_NAME).ok())
+    }
+
+    /// Loads and parses a session file. Any failure -- missing file,
+    /// unreadable file, malformed TOML -- is logged and treated the same
+    /// as "no prior session", never as a reason to panic.
+    pub fn load(path: &Path) -> SessionState {
+        let mut contents = String::new();
+        if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            print_err!("no session to restore at {}: {}", path.display(), e);
+            return SessionState::empty();
+        }
+
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            print_err!("session file {} is not valid toml: {}", path.display(), e);
+            SessionState::empty()
+        })
+    }
+
+    pub fn save(&self, path: &Path) {
+        if let Some(parent) = path.parent() {
+            if let Err(e) = ::std::fs::create_dir_all(parent) {
+                print_err!("couldn't create session dir {}: {}", parent.display(), e);
+                return;
+            }
+        }
+        let encoded = match toml::to_string(self) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                print_err!("couldn't encode session: {}", e);
+                return;
+            }
+        };
+        match File::create(path).and_then(|mut f| f.write_all(encoded.as_bytes())) {
+            Ok(_) => {}
+            Err(e) => print_err!("couldn't save session to {}: {}", path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_missing_file_returns_empty_session() {
+        let path = ::std::env::temp_dir().join("xi-session-test-missing.toml");
+        let _ = ::std::fs::remove_file(&path);
+
+        let session = SessionState::load(&path);
+
+        assert_eq!(session.id_counter, 0);
+        assert!(session.tabs.is_empty());
+        assert!(session.kill_ring.is_none());
+    }
+
+    #[test]
+    fn load_malformed_file_returns_empty_session() {
+        let path = ::std::env::temp_dir().join("xi-session-test-malformed.toml");
+        File::create(&path).unwrap().write_all(b"this is not valid toml { } [[[").unwrap();
+
+        let session = SessionState::load(&path);
+
+        assert_eq!(session.id_counter, 0);
+        assert!(session.tabs.is_empty());
+
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let path = ::std::env::temp_dir().join("xi-session-test-round-trip.toml");
+        let session = SessionState {
+            id_counter: 3,
+            tabs: vec![TabSession { path: "/tmp/a.rs".to_string(), selection_start: 1, selection_end: 4 }],
+            kill_ring: Some("killed text".to_string()),
+        };
+
+        session.save(&path);
+        let loaded = SessionState::load(&path);
+
+        assert_eq!(loaded.id_counter, session.id_counter);
+        assert_eq!(loaded.tabs.len(), 1);
+        assert_eq!(loaded.tabs[0].path, "/tmp/a.rs");
+        assert_eq!(loaded.kill_ring, session.kill_ring);
+
+        let _ = ::std::fs::remove_file(&path);
+    }
+}
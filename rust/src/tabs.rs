@@ -15,20 +15,52 @@
 //! A container for all the tabs being edited. Also functions as main dispatch for RPC.
 
 use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 use serde_json::Value;
 use serde_json::builder::ObjectBuilder;
 
 use xi_rope::rope::Rope;
 use editor::Editor;
 use rpc::{TabCommand, EditCommand};
-use run_plugin::PluginPeer;
+use run_plugin::{self, PluginPeer};
+use plugin_catalog::PluginCatalog;
+use plugin_manifest::{Capability, Manifest};
+use plugin_request::RequestTable;
+use lsp::LspClient;
+use session::{SessionState, TabSession};
 use MainPeer;
 
+/// Minimum time between two session writes triggered by edits, so a burst
+/// of keystrokes doesn't turn into a burst of file writes.
+const SESSION_SAVE_DEBOUNCE: Duration = Duration::from_secs(5);
+
 pub struct Tabs {
     tabs: BTreeMap<String, Arc<Mutex<Editor>>>,
     id_counter: usize,
     kill_ring: Mutex<Rope>,
+    catalog: PluginCatalog,
+    // The live `PluginCtx` for every plugin currently running for each tab.
+    // Shared with `TabCtx` so that servicing a real inbound call from a
+    // plugin reuses the same manifest+peer for its whole connection,
+    // instead of rebuilding a trust-everything, peer-less context per call.
+    plugin_ctxs: Arc<Mutex<BTreeMap<String, Vec<Arc<PluginCtx>>>>>,
+    // Shared with every `PluginCtx` so a request started on one thread can
+    // be suspended, resumed, or force-finished from another.
+    plugin_requests: Arc<Mutex<RequestTable>>,
+    // Language server executables, keyed by the syntax name `Editor`
+    // reports for a buffer (e.g. "rust", "python").
+    lsp_servers: BTreeMap<String, PathBuf>,
+    // One running language server per tab that has one; a tab with no
+    // matching `lsp_servers` entry, or whose server has crashed, simply
+    // has no entry here.
+    lsp_clients: BTreeMap<String, LspClient>,
+    // Where the open-tab session is persisted; `None` means this `Tabs`
+    // was not asked to restore or save a session (e.g. in tests).
+    session_path: Option<PathBuf>,
+    last_session_save: Mutex<Instant>,
 }
 
 pub struct TabCtx<'a> {
@@ -36,12 +68,36 @@ pub struct TabCtx<'a> {
     kill_ring: &'a Mutex<Rope>,
     rpc_peer: &'a MainPeer,
     self_ref: Arc<Mutex<Editor>>,
+    plugin_requests: Arc<Mutex<RequestTable>>,
+    plugin_ctxs: Arc<Mutex<BTreeMap<String, Vec<Arc<PluginCtx>>>>>,
+}
+
+/// Wire framing used for notifications and requests sent to a plugin.
+/// Negotiated once, right after the plugin connects; see
+/// `PluginCtx::on_plugin_connect` and `PluginCtx::on_plugin_encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginEncoding {
+    /// `serde_json::Value`, framed as in the original protocol. The
+    /// default, and the only encoding a plugin needs to support.
+    Json,
+    /// MessagePack via `rmp-serde`, for plugins that opt in. Much smaller
+    /// and cheaper to encode for high-frequency calls like
+    /// `set_line_fg_spans` on a large buffer.
+    MsgPack,
 }
 
 pub struct PluginCtx {
+    tab: String,
     main_peer: MainPeer,
-    plugin_peer: Option<PluginPeer>,
+    plugin_peer: PluginPeer,
     editor: Arc<Mutex<Editor>>,
+    manifest: Manifest,
+    encoding: Mutex<PluginEncoding>,
+    requests: Arc<Mutex<RequestTable>>,
+    // So this `PluginCtx` can remove itself from the tab's plugin list on
+    // disconnect, without `Tabs` needing to be involved from a background
+    // thread.
+    plugin_ctxs: Arc<Mutex<BTreeMap<String, Vec<Arc<PluginCtx>>>>>,
 }
 
 impl Tabs {
@@ -50,6 +106,115 @@ impl Tabs {
             tabs: BTreeMap::new(),
             id_counter: 0,
             kill_ring: Mutex::new(Rope::from("")),
+            catalog: PluginCatalog::empty(),
+            plugin_ctxs: Arc::new(Mutex::new(BTreeMap::new())),
+            plugin_requests: Arc::new(Mutex::new(RequestTable::new())),
+            lsp_servers: BTreeMap::new(),
+            lsp_clients: BTreeMap::new(),
+            session_path: None,
+            last_session_save: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Like `new`, but also scans `plugins_dir` for plugin manifests so
+    /// that matching plugins are autostarted as tabs are opened and edited.
+    pub fn with_plugins_dir(plugins_dir: &Path) -> Tabs {
+        let mut tabs = Tabs::new();
+        tabs.catalog = PluginCatalog::scan(plugins_dir);
+        tabs
+    }
+
+    /// Restores whatever tabs were open last time the editor quit, reading
+    /// the session from the OS config directory. If there isn't a usable
+    /// one, this starts empty and never saves. Further edits are saved back
+    /// to the same location, at most once per `SESSION_SAVE_DEBOUNCE`, and
+    /// on every `DeleteTab`. A missing or malformed session
+    /// file just starts clean -- this never panics.
+    pub fn with_session() -> Tabs {
+        let mut tabs = Tabs::new();
+        let session_path = SessionState::default_path();
+        tabs.session_path = session_path.clone();
+
+        let session = match session_path {
+            Some(ref path) if path.exists() => SessionState::load(path),
+            _ => SessionState::empty(),
+        };
+
+        tabs.id_counter = session.id_counter;
+        if let Some(ref kill_ring) = session.kill_ring {
+            tabs.kill_ring = Mutex::new(Rope::from(kill_ring.as_str()));
+        }
+        for tab_session in session.tabs {
+            tabs.restore_tab(tab_session);
+        }
+        tabs
+    }
+
+    /// Reopens a single tab from a saved `TabSession`. Failing to read the
+    /// backing file is logged and simply drops that tab from the restored
+    /// set, rather than aborting the rest of the session restore.
+    fn restore_tab(&mut self, tab_session: TabSession) {
+        let path = PathBuf::from(&tab_session.path);
+        match Editor::open(&path) {
+            Ok(mut editor) => {
+                editor.set_selection(tab_session.selection_start, tab_session.selection_end);
+                let tabname = self.id_counter.to_string();
+                self.id_counter += 1;
+                self.tabs.insert(tabname, Arc::new(Mutex::new(editor)));
+            }
+            Err(e) => print_err!("couldn't restore tab for {}: {}", path.display(), e),
+        }
+    }
+
+    /// Registers the language server executable to launch for buffers
+    /// whose detected syntax is `language`. Replaces any previous
+    /// registration for that language.
+    pub fn register_lsp_server(&mut self, language: &str, exec_path: PathBuf) {
+        self.lsp_servers.insert(language.to_string(), exec_path);
+    }
+
+    /// A snapshot of the currently open tabs, suitable for writing out as
+    /// a session.
+    fn session_state(&self) -> SessionState {
+        let tabs = self.tabs.iter().filter_map(|(_, editor)| {
+            let editor = editor.lock().unwrap();
+            let path = match editor.path() {
+                Some(path) => path.to_string_lossy().into_owned(),
+                None => return None,
+            };
+            let (start, end) = editor.selection();
+            Some(TabSession { path: path, selection_start: start, selection_end: end })
+        }).collect();
+
+        SessionState {
+            id_counter: self.id_counter,
+            tabs: tabs,
+            kill_ring: Some(self.kill_ring.lock().unwrap().to_string()),
+        }
+    }
+
+    fn save_session(&self) {
+        if let Some(ref path) = self.session_path {
+            self.session_state().save(path);
+            *self.last_session_save.lock().unwrap() = Instant::now();
+        }
+    }
+
+    /// Saves the session if it's been at least `SESSION_SAVE_DEBOUNCE`
+    /// since the last save, so a flurry of edits writes the file at most
+    /// once per interval instead of on every keystroke. There is no timer:
+    /// edits made after the last write are saved only by a later edit once
+    /// the interval has passed, by a `DeleteTab`, or when `Tabs` is dropped.
+    fn maybe_save_session(&self) {
+        if self.session_path.is_none() {
+            return;
+        }
+        let due = {
+            let last = self.last_session_save.lock().unwrap();
+            last.elapsed() >= SESSION_SAVE_DEBOUNCE
+        };
+        if due {
+            self.save_session();
         }
     }
 
@@ -57,7 +222,7 @@ impl Tabs {
         use rpc::TabCommand::*;
 
         match cmd {
-            NewTab => Some(Value::String(self.do_new_tab())),
+            NewTab => Some(Value::String(self.do_new_tab(&rpc_peer))),
 
             DeleteTab { tab_name } => {
                 self.do_delete_tab(tab_name);
@@ -68,28 +233,43 @@ impl Tabs {
         }
     }
 
-    fn do_new_tab(&mut self) -> String {
-        self.new_tab()
+    fn do_new_tab(&mut self, rpc_peer: &MainPeer) -> String {
+        let tabname = self.new_tab();
+        self.spawn_matching_plugins(&tabname, rpc_peer);
+        tabname
     }
 
     fn do_delete_tab(&mut self, tab: &str) {
         self.delete_tab(tab);
+        self.save_session();
     }
 
     fn do_edit(&mut self, tab: &str, cmd: EditCommand, rpc_peer: &MainPeer)
             -> Option<Value> {
-        if let Some(editor) = self.tabs.get(tab) {
+        if self.tabs.get(tab).is_some() {
+            self.spawn_matching_plugins(tab, rpc_peer);
+            self.ensure_lsp_client(tab);
+        }
+        let result = if let Some(editor) = self.tabs.get(tab) {
             let tab_ctx = TabCtx {
                 tab: tab,
                 kill_ring: &self.kill_ring,
                 rpc_peer: rpc_peer,
                 self_ref: editor.clone(),
+                plugin_requests: self.plugin_requests.clone(),
+                plugin_ctxs: self.plugin_ctxs.clone(),
             };
             editor.lock().unwrap().do_rpc(cmd, tab_ctx)
         } else {
             print_err!("tab not found: {}", tab);
             None
+        };
+
+        if result.is_some() {
+            self.sync_lsp_client(tab);
+            self.maybe_save_session();
         }
+        result
     }
 
     fn new_tab(&mut self) -> String {
@@ -102,6 +282,156 @@ impl Tabs {
 
     fn delete_tab(&mut self, tabname: &str) {
         self.tabs.remove(tabname);
+        self.plugin_ctxs.lock().unwrap().remove(tabname);
+        // Any response a plugin sends for this tab from now on has nowhere
+        // to go; mark its in-flight requests finished so they're dropped
+        // instead of left `Blocked`/`Suspended` forever.
+        self.plugin_requests.lock().unwrap().finish_tab(tabname);
+        if let Some(client) = self.lsp_clients.remove(tabname) {
+            client.shutdown();
+        }
+    }
+
+    /// Launches the language server registered for `tab`'s detected
+    /// syntax, if there is one and it isn't already running. A server
+    /// that fails to start is logged and simply leaves the tab without
+    /// LSP features, rather than disabling anything else.
+    fn ensure_lsp_client(&mut self, tab: &str) {
+        if self.lsp_clients.contains_key(tab) {
+            return;
+        }
+        let editor = match self.tabs.get(tab) {
+            Some(editor) => editor.clone(),
+            None => return,
+        };
+        let (language, uri, text) = {
+            let editor = editor.lock().unwrap();
+            let language = match editor.syntax_name() {
+                Some(language) => language.to_string(),
+                None => return,
+            };
+            let uri = match editor.path() {
+                Some(path) => format!("file://{}", path.display()),
+                None => return,
+            };
+            (language, uri, editor.plugin_get_document())
+        };
+        let exec_path = match self.lsp_servers.get(&language) {
+            Some(exec_path) => exec_path.clone(),
+            None => return,
+        };
+
+        match LspClient::launch(&language, &exec_path.to_string_lossy()) {
+            Ok(mut client) => {
+                if let Err(e) = client.did_open(&uri, &text) {
+                    print_err!("lsp server for {} failed on didOpen: {}", language, e);
+                }
+                self.lsp_clients.insert(tab.to_string(), client);
+            }
+            Err(e) => print_err!("failed to start lsp server for {}: {}", language, e),
+        }
+    }
+
+    /// Tells `tab`'s language server (if any) about the edit that was
+    /// just applied, and feeds back any diagnostics/semantic tokens it's
+    /// sent since the last sync through the plugin span sink. A server
+    /// that errors here is torn down so a crash only costs this tab its
+    /// LSP features, not the rest of the editor.
+    fn sync_lsp_client(&mut self, tab: &str) {
+        let editor = match self.tabs.get(tab) {
+            Some(editor) => editor.clone(),
+            None => return,
+        };
+        let mut failed = false;
+        if let Some(client) = self.lsp_clients.get_mut(tab) {
+            let uri = {
+                let editor = editor.lock().unwrap();
+                editor.path().map(|p| format!("file://{}", p.display()))
+            };
+            if let Some(uri) = uri {
+                let delta = editor.lock().unwrap().plugin_last_delta();
+                if let Some((start_line, start_col, end_line, end_col, replacement)) = delta {
+                    if let Err(e) = client.did_change(&uri, start_line, start_col, end_line, end_col, &replacement) {
+                        print_err!("lsp server for {} failed on didChange: {}", client.language(), e);
+                        failed = true;
+                    }
+                }
+            }
+            if !failed {
+                for line_spans in client.poll_spans() {
+                    editor.lock().unwrap().plugin_set_line_fg_spans(line_spans.line_num, &line_spans.spans);
+                }
+            }
+        }
+        if failed {
+            self.lsp_clients.remove(tab);
+        }
+    }
+
+    /// Consults the plugin catalog for `tab` and starts any plugin that
+    /// matches the buffer's current state and isn't already running for
+    /// this tab. Spawn failures are logged and otherwise ignored, so a
+    /// broken plugin doesn't block editing.
+    fn spawn_matching_plugins(&mut self, tab: &str, rpc_peer: &MainPeer) {
+        let editor = match self.tabs.get(tab) {
+            Some(editor) => editor.clone(),
+            None => return,
+        };
+        let (file_path, n_lines) = {
+            let editor = editor.lock().unwrap();
+            (editor.path().map(|p| p.to_path_buf()), editor.plugin_n_lines())
+        };
+
+        let running_names: Vec<String> = self.plugin_ctxs.lock().unwrap()
+            .get(tab)
+            .map(|ctxs| ctxs.iter().map(|ctx| ctx.plugin_name().to_string()).collect())
+            .unwrap_or_else(Vec::new);
+
+        for manifest in self.catalog.matching(file_path.as_ref().map(PathBuf::as_path), n_lines) {
+            if running_names.iter().any(|name| name == &manifest.name) {
+                continue;
+            }
+            match run_plugin::start_plugin(&manifest.exec_path) {
+                Ok((peer, incoming)) => {
+                    let ctx = Arc::new(PluginCtx {
+                        tab: tab.to_string(),
+                        main_peer: rpc_peer.clone(),
+                        plugin_peer: peer,
+                        editor: editor.clone(),
+                        manifest: manifest.clone(),
+                        encoding: Mutex::new(PluginEncoding::Json),
+                        requests: self.plugin_requests.clone(),
+                        plugin_ctxs: self.plugin_ctxs.clone(),
+                    });
+                    ctx.on_plugin_connect();
+                    self.plugin_ctxs.lock().unwrap()
+                        .entry(tab.to_string())
+                        .or_insert_with(Vec::new)
+                        .push(ctx.clone());
+
+                    // This context -- manifest, peer, and encoding included
+                    // -- is the one that services every inbound call from
+                    // this plugin for as long as it stays connected; see
+                    // `PluginCtx::handle_incoming`.
+                    thread::spawn(move || {
+                        for message in incoming {
+                            ctx.handle_incoming(&message);
+                        }
+                        ctx.handle_disconnect();
+                    });
+                }
+                Err(e) => print_err!("failed to start plugin {}: {}", manifest.name, e),
+            }
+        }
+    }
+}
+
+/// A `Tabs` going away (process shutdown, or just dropped by an embedder)
+/// force-flushes the session, so an edit inside the debounce window isn't
+/// lost if the editor quits before the next save would have fired.
+impl Drop for Tabs {
+    fn drop(&mut self) {
+        self.save_session();
     }
 }
 
@@ -127,41 +457,176 @@ impl<'a> TabCtx<'a> {
         self.self_ref.clone()
     }
 
-    pub fn to_plugin_ctx(&self) -> PluginCtx {
-        PluginCtx {
-            main_peer: self.rpc_peer.clone(),
-            plugin_peer: None,
-            editor: self.get_self_ref(),
-        }
+    /// The live `PluginCtx` for `plugin_name` in this tab, if that plugin
+    /// is currently connected. There's no manifest-less fallback: a
+    /// plugin that isn't in this list isn't connected, and has nothing to
+    /// be serviced on its behalf.
+    pub fn to_plugin_ctx(&self, plugin_name: &str) -> Option<Arc<PluginCtx>> {
+        self.plugin_ctxs.lock().unwrap()
+            .get(self.tab)
+            .and_then(|ctxs| ctxs.iter().find(|ctx| ctx.plugin_name() == plugin_name))
+            .cloned()
     }
 }
 
 impl PluginCtx {
-    pub fn on_plugin_connect(&mut self, peer: PluginPeer) {
+    fn on_plugin_connect(&self) {
         let buf_size = self.editor.lock().unwrap().plugin_buf_size();
-        peer.send_rpc_notification("ping_from_editor", &Value::Array(vec![Value::U64(buf_size as u64)]));
-        self.plugin_peer = Some(peer);
+        self.plugin_peer.send_rpc_notification("ping_from_editor",
+            &ObjectBuilder::new()
+                .insert("buf_size", buf_size)
+                .insert("supported_encodings", vec!["json", "msgpack"])
+                .unwrap());
+    }
+
+    /// Called once the plugin has answered the `ping_from_editor` handshake
+    /// with the encoding it wants to use. An unrecognized encoding leaves
+    /// the connection on the JSON fallback.
+    fn on_plugin_encoding(&self, encoding: &str) {
+        *self.encoding.lock().unwrap() = match encoding {
+            "msgpack" => PluginEncoding::MsgPack,
+            _ => PluginEncoding::Json,
+        };
     }
 
-    // Note: the following are placeholders for prototyping, and are not intended to
-    // deal with asynchrony or be efficient.
+    /// Routes a single message read off this plugin's connection to
+    /// whichever handler answers it. This `PluginCtx` -- manifest, peer,
+    /// and negotiated encoding included -- is reused for every message on
+    /// this connection, not rebuilt per call.
+    fn handle_incoming(&self, message: &Value) {
+        let method = message.find("method").and_then(Value::as_string);
+        let params = message.find("params");
+        match method {
+            Some("ping_from_editor_response") => {
+                if let Some(encoding) = params.and_then(|p| p.find("encoding")).and_then(Value::as_string) {
+                    self.on_plugin_encoding(encoding);
+                }
+            }
+            Some("resume") => {
+                if let Some(id) = params.and_then(|p| p.find("id")).and_then(Value::as_u64) {
+                    self.on_plugin_resume(id);
+                }
+            }
+            Some("n_lines") => { self.n_lines(); }
+            Some("get_line") => {
+                if let Some(line_num) = params.and_then(|p| p.find("line_num")).and_then(Value::as_u64) {
+                    self.get_line(line_num as usize);
+                }
+            }
+            Some("set_line_fg_spans") => {
+                let line_num = params.and_then(|p| p.find("line_num")).and_then(Value::as_u64);
+                let spans = params.and_then(|p| p.find("spans")).cloned();
+                if let (Some(line_num), Some(spans)) = (line_num, spans) {
+                    self.set_line_fg_spans(line_num as usize, spans);
+                }
+            }
+            Some("alert") => {
+                if let Some(msg) = params.and_then(|p| p.find("msg")).and_then(Value::as_string) {
+                    self.alert(msg);
+                }
+            }
+            _ => print_err!("unrecognized message from plugin {}: {:?}", self.plugin_name(), method),
+        }
+    }
+
+    /// Called once this plugin's connection has closed, so any of its
+    /// requests still `Blocked`/`Suspended` don't linger forever waiting
+    /// for a response that will never arrive, and it's no longer reused
+    /// for a plugin that's since reconnected.
+    fn handle_disconnect(&self) {
+        if let Some(running) = self.plugin_ctxs.lock().unwrap().get_mut(&self.tab) {
+            running.retain(|ctx| ctx.plugin_name() != self.plugin_name());
+        }
+        self.requests.lock().unwrap().finish_plugin(&self.tab, self.plugin_name());
+    }
 
-    pub fn n_lines(&self) -> usize {
-        self.editor.lock().unwrap().plugin_n_lines()
+    // Each of the following starts a plugin request and returns
+    // immediately, without ever holding the `Editor` lock across the round
+    // trip to the plugin: `begin()` records the request `Blocked` and hands
+    // the actual work to a background thread, which locks the `Editor`
+    // only long enough to compute the answer, marks the request `Suspended`,
+    // and sends the response. The request only becomes `Finished` when the
+    // plugin's own acknowledgement for that id comes back through
+    // `on_plugin_resume` -- there's no synchronous finish anywhere in this
+    // path.
+
+    pub fn n_lines(&self) {
+        self.dispatch_request("n_lines_response", |editor| {
+            Value::U64(editor.lock().unwrap().plugin_n_lines() as u64)
+        });
     }
 
-    pub fn get_line(&self, line_num: usize) -> String {
-        self.editor.lock().unwrap().plugin_get_line(line_num)
+    pub fn get_line(&self, line_num: usize) {
+        self.dispatch_request("get_line_response", move |editor| {
+            Value::String(editor.lock().unwrap().plugin_get_line(line_num))
+        });
     }
 
-    pub fn set_line_fg_spans(&self, line_num: usize, spans: &Value) {
-        self.editor.lock().unwrap().plugin_set_line_fg_spans(line_num, spans);
+    pub fn set_line_fg_spans(&self, line_num: usize, spans: Value) {
+        if !self.allows(Capability::SetLineFgSpans) {
+            print_err!("plugin lacks the set_line_fg_spans capability, ignoring");
+            return;
+        }
+        self.dispatch_request("set_line_fg_spans_response", move |editor| {
+            editor.lock().unwrap().plugin_set_line_fg_spans(line_num, &spans);
+            Value::Null
+        });
+    }
+
+    /// Begins a request, then spawns a thread that computes `compute`'s
+    /// answer against the `Editor`, suspends the request, and sends the
+    /// response -- all off of whatever thread called this method, which
+    /// returns as soon as the request is recorded.
+    fn dispatch_request<F>(&self, method: &'static str, compute: F)
+        where F: FnOnce(&Arc<Mutex<Editor>>) -> Value + Send + 'static
+    {
+        let id = self.requests.lock().unwrap().begin(&self.tab, Some(self.plugin_name()));
+        let editor = self.editor.clone();
+        let requests = self.requests.clone();
+        let peer = self.plugin_peer.clone();
+        let encoding = *self.encoding.lock().unwrap();
+
+        thread::spawn(move || {
+            let result = compute(&editor);
+            requests.lock().unwrap().suspend(id);
+            let params = ObjectBuilder::new().insert("id", id).insert("result", result).unwrap();
+            match encoding {
+                PluginEncoding::Json => peer.send_rpc_notification(method, &params),
+                PluginEncoding::MsgPack => peer.send_rpc_notification_msgpack(method, &params),
+            }
+        });
+    }
+
+    /// Called when the plugin's own acknowledgement for `id` arrives -- the
+    /// one real resume point in this request's lifecycle. An ack for an id
+    /// that isn't `Suspended` (already finished, or never existed) is stale
+    /// and is dropped rather than acted on.
+    fn on_plugin_resume(&self, id: u64) -> bool {
+        let mut requests = self.requests.lock().unwrap();
+        if requests.is_suspended(id) {
+            requests.finish(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn plugin_name(&self) -> &str {
+        &self.manifest.name
     }
 
     pub fn alert(&self, msg: &str) {
+        if !self.allows(Capability::Alert) {
+            print_err!("plugin lacks the alert capability, ignoring");
+            return;
+        }
         self.main_peer.send_rpc_notification("alert",
             &ObjectBuilder::new()
                 .insert("msg", msg)
                 .unwrap());
     }
+
+    fn allows(&self, capability: Capability) -> bool {
+        self.manifest.allows(&capability)
+    }
 }